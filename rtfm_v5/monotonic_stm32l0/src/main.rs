@@ -1,21 +1,77 @@
-#![no_main]
-#![no_std]
+// `no_std`/`no_main` only apply to the embedded build: `cargo test` runs the
+// pure-logic unit tests in `monotonic_stm32l0`/`rtc_monotonic` against the
+// host's own `std` and test harness instead, since none of those tests touch
+// hardware registers.
+#![cfg_attr(not(test), no_main)]
+#![cfg_attr(not(test), no_std)]
 
+// `U32Ext` is used either way; `LinkedMonotonic` and friends go unused when
+// `rtc-monotonic` swaps in `rtc_monotonic::RtcMonotonic` below instead.
+#[cfg_attr(feature = "rtc-monotonic", allow(dead_code))]
 mod monotonic_stm32l0;
+// Low-power alternative to `monotonic_stm32l0`: ticks from the RTC domain
+// instead of a linked timer pair, so `#[idle]` can WFI instead of
+// busy-looping. Selected instead of the default monotonic by building with
+// `--features rtc-monotonic`; see its module docs for why it trades away
+// `trace`-style RTT logging to do so. Unused (but still compiled, so its
+// own unit tests keep running) when that feature is off.
+#[cfg_attr(not(feature = "rtc-monotonic"), allow(dead_code))]
+mod rtc_monotonic;
 
-use monotonic_stm32l0::{Duration, Instant, LinkedTim2Tim3};
+use monotonic_stm32l0::U32Ext;
+#[cfg(not(test))]
 use panic_rtt_target as _;
 use rtic::app;
 use rtt_target::{rprintln, rtt_init_print};
 use stm32l0xx_hal::prelude::*;
-use stm32l0xx_hal::{pac, rcc::Config, timer::LinkedTimerPair};
+use stm32l0xx_hal::{pac, rcc::Config};
+#[cfg(not(feature = "rtc-monotonic"))]
+use stm32l0xx_hal::timer::LinkedTimerPair;
 
+/// The monotonic clock ticks at 1 kHz (millisecond resolution).
+///
+/// Unused when built with `rtc-monotonic`, since [`rtc_monotonic::RtcMonotonic`]
+/// ticks at its own fixed [`rtc_monotonic::RTC_FREQ`] instead.
+#[cfg(not(feature = "rtc-monotonic"))]
+const MONO_FREQ: u32 = 1_000;
+
+#[cfg(not(feature = "rtc-monotonic"))]
+type Mono = monotonic_stm32l0::LinkedTim2Tim3<MONO_FREQ>;
+#[cfg(not(feature = "rtc-monotonic"))]
+type Instant = monotonic_stm32l0::Instant<MONO_FREQ>;
+
+#[cfg(feature = "rtc-monotonic")]
+type Mono = rtc_monotonic::RtcMonotonic;
+#[cfg(feature = "rtc-monotonic")]
+type Instant = rtc_monotonic::Instant;
+
+/// Arms `Mono`'s compare mechanism for `target`. Returns `false` (without
+/// arming) if `target` is already at or behind now, mirroring
+/// `try_set_compare_at`'s contract.
+#[cfg(not(feature = "rtc-monotonic"))]
+fn arm_foo_at(target: Instant) -> bool {
+    // Split the absolute tick into the low 16 bits and MSB ("overflow")
+    // half that `LinkedMonotonic::try_set_compare_at` expects.
+    let raw = target.ticks();
+    Mono::try_set_compare_at(raw, (raw >> 16) as u16)
+}
+
+/// Arms `Mono`'s compare mechanism for `target`. Returns `false` (without
+/// arming) if `target` is already at or behind now, mirroring
+/// `try_set_compare_at`'s contract.
+#[cfg(feature = "rtc-monotonic")]
+fn arm_foo_at(target: Instant) -> bool {
+    Mono::try_set_compare_at(target.ticks())
+}
+
+#[cfg(not(test))]
 #[app(
     device = stm32l0xx_hal::pac,
     peripherals = true,
-    monotonic = crate::monotonic_stm32l0::LinkedTim2Tim3,
+    monotonic = crate::Mono,
 )]
 const APP: () = {
+    #[cfg(not(feature = "rtc-monotonic"))]
     #[init(spawn = [foo])]
     fn init(cx: init::Context) {
         // Initialize RTT
@@ -34,7 +90,11 @@ const APP: () = {
 
         // Use linked timer as RTIC monotonic clock.
         rprintln!("Initialize monotonic timer (TIM2/TIM3)");
-        LinkedTim2Tim3::initialize(linked_timer);
+        Mono::initialize(linked_timer, &rcc);
+
+        // Allow the LSB timer's CC1 channel (used by `LinkedTim2Tim3` to
+        // schedule wakeups beyond a single overflow window) to interrupt.
+        unsafe { pac::NVIC::unmask(pac::Interrupt::TIM2) };
 
         rprintln!("init(baseline = {:?})", cx.start);
 
@@ -44,15 +104,89 @@ const APP: () = {
         rprintln!("Init done!");
     }
 
-    #[task(schedule = [foo])]
+    #[cfg(feature = "rtc-monotonic")]
+    #[init(spawn = [foo])]
+    fn init(cx: init::Context) {
+        // Initialize RTT
+        rtt_init_print!();
+        rprintln!("Init");
+
+        // Get peripherals
+        let dp: pac::Peripherals = cx.device;
+
+        // Clock configuration. Use HSI at 16 MHz. The RTC itself runs off
+        // its own LSE/LSI backup-domain clock, independent of this.
+        rprintln!("Set up clock (16 MHz)");
+        let mut rcc = dp.RCC.freeze(Config::hsi16());
+
+        // Use the RTC as the RTIC monotonic clock, so `#[idle]` can WFI
+        // instead of busy-looping to keep it alive.
+        rprintln!("Initialize monotonic timer (RTC)");
+        let rtc = stm32l0xx_hal::rtc::Rtc::new(dp.RTC, &mut rcc);
+        Mono::initialize(rtc);
+
+        // The RTC's alarm/tamper/wakeup-timer interrupts share one vector.
+        unsafe { pac::NVIC::unmask(pac::Interrupt::RTC) };
+
+        rprintln!("init(baseline = {:?})", cx.start);
+
+        // Spawn task "foo"
+        cx.spawn.foo().unwrap();
+
+        rprintln!("Init done!");
+    }
+
+    #[task(spawn = [foo])]
     fn foo(cx: foo::Context) {
-        let now = Instant::now();
-        rprintln!("foo(scheduled = {:?}, now = {:?})", cx.scheduled, now);
-        cx.schedule
-            .foo(cx.scheduled + Duration::from_cycles(6301))  // TODO: Does not work with values >6300
-            .unwrap();
+        let now = Mono::now();
+        rprintln!("foo(now = {:?})", now);
+
+        // At the monotonic's tick rate this is well beyond a single
+        // compare channel's overflow window (65_536 ticks for the linked
+        // timer pair, ~64 s for the RTC wakeup timer). Arming the compare
+        // mechanism directly -- rather than RTIC's own timer queue -- is
+        // what makes scheduling this far out work.
+        let target = now + 100u32.secs();
+        if !arm_foo_at(target) {
+            // Target is already due: the compare mechanism wasn't armed,
+            // so nothing will wake the bound task for it. Spawn right away
+            // instead.
+            cx.spawn.foo().ok();
+        }
+    }
+
+    // Wakes up the core once the linked timer's CC1 compare channel
+    // matches. May fire once per LSB overflow window before the actual
+    // scheduled overflow is reached; `has_compared` tells those apart.
+    #[cfg(not(feature = "rtc-monotonic"))]
+    #[task(binds = TIM2, priority = 1, spawn = [foo])]
+    fn tim2(cx: tim2::Context) {
+        if Mono::has_compared() {
+            Mono::clear_irq();
+            rprintln!("tim2: compare target reached");
+            cx.spawn.foo().ok();
+        } else {
+            Mono::clear_irq();
+        }
+    }
+
+    // Wakes up the core once the RTC wakeup timer fires. May fire more than
+    // once before the actual scheduled target is reached, since a target
+    // more than one period out rearms automatically; `has_compared` tells
+    // those apart.
+    #[cfg(feature = "rtc-monotonic")]
+    #[task(binds = RTC, priority = 1, spawn = [foo])]
+    fn rtc_wakeup(cx: rtc_wakeup::Context) {
+        if Mono::has_compared() {
+            Mono::clear_irq();
+            rprintln!("rtc_wakeup: compare target reached");
+            cx.spawn.foo().ok();
+        } else {
+            Mono::clear_irq();
+        }
     }
 
+    #[cfg(not(feature = "rtc-monotonic"))]
     #[idle]
     fn idle(_: idle::Context) -> ! {
         // The default implementation of #[idle] uses WFI to go to deep sleep.
@@ -61,6 +195,18 @@ const APP: () = {
         loop {}
     }
 
+    #[cfg(feature = "rtc-monotonic")]
+    #[idle]
+    fn idle(_: idle::Context) -> ! {
+        // Unlike the default build, this one ticks from the RTC rather than
+        // a core-clocked timer and never traces from `now()`/compare-arm on
+        // the hot path, so it's safe to actually sleep here instead of
+        // busy-looping to keep RTT alive.
+        loop {
+            cortex_m::asm::wfi();
+        }
+    }
+
     extern "C" {
         fn SPI1();
     }
@@ -3,294 +3,450 @@
 //! Note: The STM32L0 only has 16 bit timers. But we can link together two such
 //! timers to form a 32 bit timer.
 
-// TODO: Correctness / bounds docs for Instant / Duration
-
-use core::u32;
-use core::{
-    cmp::Ordering,
-    convert::{Infallible, TryInto},
-    fmt, ops,
-};
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicU16, AtomicU32, Ordering as AtomicOrdering};
+
+use fugit::{TimerDurationU32, TimerInstantU32};
 use rtic::Monotonic;
+#[cfg(feature = "trace")]
 use rtt_target::rprintln;
-use stm32l0xx_hal::{pac, timer::LinkedTimerPair};
+use stm32l0xx_hal::{pac, rcc::Rcc, timer::LinkedTimerPair};
+
+/// The MSB ("overflow count") of the tick currently armed on the LSB timer's
+/// CC1 channel, if any. Used by [`LinkedMonotonic::has_compared`] to tell a
+/// hardware match in the current LSB period apart from the actual target
+/// overflow being reached.
+static ARMED_COMPARE_OVERFLOWS: AtomicU16 = AtomicU16::new(0);
 
-/// Implementor of the `rtic::Monotonic` traits and used to consume the timer
-/// to not allow for erroneous configuration.
+/// The timer input clock (in Hz) that [`LinkedMonotonic::initialize`]
+/// programmed the LSB timer's prescaler from. Cached so that [`Monotonic::ratio`]
+/// can report the real relationship between `FREQ` and the timer's actual
+/// input clock instead of assuming they're equal.
+static TIMER_INPUT_CLOCK_HZ: AtomicU32 = AtomicU32::new(0);
+
+/// A point in time as tracked by [`LinkedMonotonic`], ticking at `FREQ` Hz.
+///
+/// # Correctness
 ///
-/// This uses TIM2/TIM3 internally as linked timers.
-pub struct LinkedTim2Tim3;
+/// The underlying 32-bit tick counter wraps around, so [`Instant`] compares
+/// and subtracts using modular ("ring") arithmetic rather than a plain
+/// integer comparison: `later - earlier` is only meaningful, and only yields
+/// the correct elapsed tick count, when the true interval between the two
+/// instants is below `1 << 31` ticks. Beyond that bound there is no way to
+/// tell "far in the future" from "far in the past" on a ring, and the result
+/// is unspecified.
+pub type Instant<const FREQ: u32> = TimerInstantU32<FREQ>;
+
+/// A span of time as tracked by [`LinkedMonotonic`], ticking at `FREQ` Hz.
+pub type Duration<const FREQ: u32> = TimerDurationU32<FREQ>;
+
+/// Register-level operations needed by [`LinkedMonotonic`], implemented for
+/// every `stm32l0xx_hal` 16-bit timer that the HAL can link into a pair
+/// (TIM2, TIM3, TIM21, TIM22). Abstracting over these lets `LinkedMonotonic`
+/// be instantiated on whichever pair is free on a given board, instead of
+/// being hardcoded to TIM2/TIM3.
+pub trait LinkedTimerChannel {
+    /// Returns the running counter value.
+    fn read_cnt() -> u16;
+    /// Resets the counter to zero.
+    fn reset_cnt();
+    /// Enables (starts) the counter.
+    fn enable();
+    /// Disables (stops) the counter.
+    fn disable();
+    /// Programs the prescaler register.
+    fn set_prescaler(psc: u16);
+    /// Programs channel 1's capture/compare register.
+    fn set_ccr1(value: u16);
+    /// Enables channel 1's compare interrupt.
+    fn enable_cc1_interrupt();
+    /// Returns whether channel 1's compare flag is set.
+    fn cc1_flag() -> bool;
+    /// Clears channel 1's compare flag.
+    fn clear_cc1_flag();
+}
 
-impl LinkedTim2Tim3 {
-    /// Initialize the timer instance.
-    pub fn initialize(timer: LinkedTimerPair<pac::TIM2, pac::TIM3>) {
-        // Explicitly drop timer instance so it cannot be reused or reconfigured.
-        drop(timer);
-    }
+macro_rules! impl_linked_timer_channel {
+    ($($TIMx:ident),+ $(,)?) => {
+        $(
+            impl LinkedTimerChannel for pac::$TIMx {
+                fn read_cnt() -> u16 {
+                    unsafe { &*Self::ptr() }.cnt.read().cnt().bits()
+                }
+
+                fn reset_cnt() {
+                    unsafe { &*Self::ptr() }.cnt.reset();
+                }
+
+                fn enable() {
+                    unsafe { &*Self::ptr() }.cr1.modify(|_, w| w.cen().set_bit());
+                }
+
+                fn disable() {
+                    unsafe { &*Self::ptr() }.cr1.modify(|_, w| w.cen().clear_bit());
+                }
+
+                fn set_prescaler(psc: u16) {
+                    unsafe { &*Self::ptr() }.psc.write(|w| w.psc().bits(psc));
+                }
+
+                fn set_ccr1(value: u16) {
+                    unsafe { &*Self::ptr() }.ccr1.write(|w| w.ccr().bits(value));
+                }
+
+                fn enable_cc1_interrupt() {
+                    unsafe { &*Self::ptr() }.dier.modify(|_, w| w.cc1ie().set_bit());
+                }
+
+                fn cc1_flag() -> bool {
+                    unsafe { &*Self::ptr() }.sr.read().cc1if().bit_is_set()
+                }
+
+                fn clear_cc1_flag() {
+                    unsafe { &*Self::ptr() }.sr.modify(|_, w| w.cc1if().clear_bit());
+                }
+            }
+        )+
+    };
 }
 
-impl Monotonic for LinkedTim2Tim3 {
-    type Instant = Instant;
+impl_linked_timer_channel!(TIM2, TIM3, TIM21, TIM22);
 
-    fn ratio() -> rtic::Fraction {
-        // monotonic * fraction = sys clock
-        // TODO: Assumes both timer and sysclock clock run at 16 MHz
-        rtic::Fraction {
-            numerator: 1,
-            denominator: 1,
-        }
-    }
+/// Implementor of the `rtic::Monotonic` trait, consuming a
+/// [`LinkedTimerPair`] to not allow for erroneous configuration.
+///
+/// Generic over any two `stm32l0xx_hal` 16-bit timers (`LSB`, `MSB`) that the
+/// HAL can link together, ticking at `FREQ` Hz. The prescaler required to
+/// reach `FREQ` from the timer's actual input clock is computed and programmed in
+/// [`initialize`](Self::initialize).
+pub struct LinkedMonotonic<LSB, MSB, const FREQ: u32> {
+    _timers: PhantomData<(LSB, MSB)>,
+}
 
-    /// Returns the current time
-    ///
-    /// # Correctness
+/// [`LinkedMonotonic`] instantiated on TIM2 (LSB) / TIM3 (MSB), the pair used
+/// by this example.
+pub type LinkedTim2Tim3<const FREQ: u32> = LinkedMonotonic<pac::TIM2, pac::TIM3, FREQ>;
+
+impl<LSB, MSB, const FREQ: u32> LinkedMonotonic<LSB, MSB, FREQ>
+where
+    LSB: LinkedTimerChannel,
+    MSB: LinkedTimerChannel,
+{
+    /// Initialize the timer instance.
     ///
-    /// This function is *allowed* to return nonsensical values if called before `reset` is invoked
-    /// by the runtime. Therefore application authors should *not* call this function during the
-    /// `#[init]` phase.
-    fn now() -> Self::Instant {
-        Instant::now()
+    /// Computes the LSB timer's prescaler so that the linked pair ticks at
+    /// exactly `FREQ` Hz given the clock configuration in `rcc`.
+    pub fn initialize(timer: LinkedTimerPair<LSB, MSB>, rcc: &Rcc) {
+        // TIM2/TIM3/TIM21/TIM22 are all clocked off APB1's timer kernel
+        // clock, not sysclk or the raw APB1 bus clock: the HAL already
+        // applies the "doubled when the APB1 prescaler divides the bus
+        // clock" rule internally (the same one its own `timers!` macros
+        // rely on), so use it instead of hand-rolling that check through an
+        // unsafe `RCC` register read.
+        let timer_clk = rcc.clocks.apb1_tim_clk().0;
+
+        assert!(
+            timer_clk >= FREQ && timer_clk % FREQ == 0,
+            "timer input clock must be an integer multiple of FREQ"
+        );
+        let psc = (timer_clk / FREQ - 1) as u16;
+
+        // Only the LSB timer's prescaler divides real time: the MSB timer is
+        // clocked off the LSB timer's update events via the internal trigger,
+        // so its own prescaler must stay at the reset value of zero.
+        LSB::set_prescaler(psc);
+
+        TIMER_INPUT_CLOCK_HZ.store(timer_clk, AtomicOrdering::Relaxed);
+
+        // Explicitly drop timer instance so it cannot be reused or reconfigured.
+        drop(timer);
     }
 
-    /// Resets the counter to *zero*
+    /// Arms the LSB timer's CC1 channel so that it raises an interrupt once
+    /// the 32-bit tick counter reaches `(overflows << 16) | ticks`.
     ///
-    /// # Safety
-    ///
-    /// This function will be called *exactly once* by the RTFM runtime after `#[init]` returns and
-    /// before tasks can start; this is also the case in multi-core applications. User code must
-    /// *never* call this function.
-    unsafe fn reset() {
-        rprintln!("LinkedTim2Tim3::reset()");
+    /// Returns `true` if the compare channel was armed. Returns `false` if
+    /// the target is already in the past (or in the current window but at or
+    /// behind `now`), in which case the caller should treat the task as
+    /// immediately due instead of waiting for an interrupt.
+    pub fn try_set_compare_at(ticks: u32, overflows: u16) -> bool {
+        let current_msb = MSB::read_cnt();
+        let current_lsb = LSB::read_cnt();
+
+        // Compare `overflows` to the running MSB using wrapping (modular)
+        // arithmetic, since the MSB counter itself wraps around every 65536
+        // overflows. A negative difference means the target's overflow
+        // count is already behind `now` -- however far behind -- so fire
+        // immediately rather than arming a compare that wouldn't match
+        // again for up to 2^16 overflows.
+        let overflow_diff = overflows.wrapping_sub(current_msb) as i16;
+        if overflow_diff < 0 || (overflow_diff == 0 && (ticks as u16) <= current_lsb) {
+            // Target is behind `now`, or in the current overflow window and
+            // already behind (or equal to) `now`: fire immediately instead
+            // of arming.
+            return false;
+        }
 
-        let tim_msb = &*pac::TIM3::ptr();
-        let tim_lsb = &*pac::TIM2::ptr();
+        ARMED_COMPARE_OVERFLOWS.store(overflows, AtomicOrdering::Relaxed);
+        LSB::set_ccr1(ticks as u16);
+        LSB::enable_cc1_interrupt();
 
-        // Pause
-        tim_msb.cr1.modify(|_, w| w.cen().clear_bit());
-        tim_lsb.cr1.modify(|_, w| w.cen().clear_bit());
-        // Reset counter
-        tim_msb.cnt.reset();
-        tim_msb.cnt.reset();
-        // Continue
-        tim_msb.cr1.modify(|_, w| w.cen().set_bit());
-        tim_lsb.cr1.modify(|_, w| w.cen().set_bit());
+        true
     }
 
-    fn zero() -> Self::Instant {
-        Instant { inner: 0 }
+    /// Clears the LSB timer's CC1 compare interrupt flag.
+    ///
+    /// Must be called from the bound interrupt handler after every match,
+    /// whether or not [`has_compared`](Self::has_compared) reports that the
+    /// target overflow was actually reached: the compare channel will keep
+    /// matching once per LSB period until the MSB ("overflow") counter
+    /// catches up with the armed target.
+    pub fn clear_irq() {
+        LSB::clear_cc1_flag();
     }
-}
 
-/// A measurement of the counter. Opaque and useful only with `Duration`.
-#[derive(Clone, Copy, Eq, PartialEq)]
-pub struct Instant {
-    inner: u32,
-}
+    /// Returns `true` if the LSB timer's CC1 channel has matched *and* the
+    /// running MSB ("overflow") counter has reached the overflow count that
+    /// was armed via [`try_set_compare_at`](Self::try_set_compare_at).
+    ///
+    /// A `false` return with the CC1 flag set means the LSB timer matched
+    /// within an earlier overflow window; the caller should
+    /// [`clear_irq`](Self::clear_irq) and keep waiting for the next one.
+    pub fn has_compared() -> bool {
+        if !LSB::cc1_flag() {
+            return false;
+        }
+
+        MSB::read_cnt() == ARMED_COMPARE_OVERFLOWS.load(AtomicOrdering::Relaxed)
+    }
 
-impl Instant {
     /// Returns an instant corresponding to "now".
-    pub fn now() -> Self {
+    pub fn now() -> Instant<FREQ> {
         loop {
-            let tim_msb = unsafe { &*pac::TIM3::ptr() };
-            let tim_lsb = unsafe { &*pac::TIM2::ptr() };
-
-            let msb = tim_msb.cnt.read().cnt().bits() as u32;
-            let lsb = tim_lsb.cnt.read().cnt().bits() as u32;
-            let msb_again = tim_msb.cnt.read().cnt().bits() as u32;
+            let msb = MSB::read_cnt() as u32;
+            let lsb = LSB::read_cnt() as u32;
 
-            rprintln!("msb {} lsb {} msba {}", msb, lsb, msb_again);
+            #[cfg(feature = "trace")]
+            rprintln!("msb {} lsb {}", msb, lsb);
 
             // Because the timer is still running at high frequency
             // between reading MSB and LSB, it's possible that LSB
             // has already overflowed. Therefore we read MSB again
             // to check that it hasn't changed.
-            let msb_again = tim_msb.cnt.read().cnt().bits() as u32;
+            let msb_again = MSB::read_cnt() as u32;
             if msb == msb_again {
-                return Instant {
-                    inner: (msb << 16) | lsb,
-                };
+                return Instant::<FREQ>::from_ticks((msb << 16) | lsb);
             }
         }
     }
 
-    /// Returns the amount of time elapsed since this instant was created.
-    pub fn elapsed(&self) -> Duration {
-        Instant::now() - *self
+    /// Returns the underlying tick count of an [`Instant`]. Thin wrapper
+    /// around [`TimerInstantU32::ticks`] kept for backward compatibility
+    /// with the pre-`fugit` API.
+    pub fn counts(instant: &Instant<FREQ>) -> u32 {
+        instant.ticks()
     }
 
-    /// Returns the underlying count
-    pub fn counts(&self) -> u32 {
-        self.inner
+    /// Returns the amount of time elapsed from `earlier` to `instant`,
+    /// wrapping around the 32-bit tick counter as needed.
+    ///
+    /// See the [`Instant`] correctness bound: this is only meaningful when
+    /// the true interval is below `1 << 31` ticks.
+    ///
+    /// This computes the wrapping difference on the raw tick counts
+    /// directly rather than going through `fugit`'s own `Sub` impl for
+    /// `TimerInstantU32`, so the documented correctness bound holds
+    /// regardless of how that impl treats a pair of instants that straddle
+    /// a wrap.
+    pub fn duration_since(instant: Instant<FREQ>, earlier: Instant<FREQ>) -> Duration<FREQ> {
+        let diff = instant.ticks().wrapping_sub(earlier.ticks());
+        Duration::<FREQ>::from_ticks(diff)
     }
 
-    /// Returns the amount of time elapsed from another instant to this one.
-    pub fn duration_since(&self, earlier: Instant) -> Duration {
-        assert!(
-            self.inner > earlier.inner,
-            "second instant is later than self"
-        );
-        Duration {
-            inner: self.inner - earlier.inner,
-        }
+    /// Creates a [`Duration`] from a raw tick count. Thin wrapper around
+    /// [`TimerDurationU32::from_ticks`] kept for backward compatibility
+    /// with the pre-`fugit` API.
+    pub fn from_cycles(cycles: u32) -> Duration<FREQ> {
+        Duration::<FREQ>::from_ticks(cycles)
     }
 }
 
-impl fmt::Debug for Instant {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_tuple("Instant")
-            .field(&(self.inner as u32))
-            .finish()
-    }
-}
+impl<LSB, MSB, const FREQ: u32> Monotonic for LinkedMonotonic<LSB, MSB, FREQ>
+where
+    LSB: LinkedTimerChannel,
+    MSB: LinkedTimerChannel,
+{
+    type Instant = Instant<FREQ>;
 
-impl ops::AddAssign<Duration> for Instant {
-    fn add_assign(&mut self, dur: Duration) {
-        self.inner = self.inner.wrapping_add(dur.inner);
+    fn ratio() -> rtic::Fraction {
+        // monotonic * fraction = timer input clock
+        let timer_clk = TIMER_INPUT_CLOCK_HZ.load(AtomicOrdering::Relaxed);
+        rtic::Fraction {
+            numerator: timer_clk,
+            denominator: FREQ,
+        }
     }
-}
 
-impl ops::Add<Duration> for Instant {
-    type Output = Self;
-    fn add(mut self, dur: Duration) -> Self {
-        self += dur;
-        self
+    /// Returns the current time
+    ///
+    /// # Correctness
+    ///
+    /// This function is *allowed* to return nonsensical values if called before `reset` is invoked
+    /// by the runtime. Therefore application authors should *not* call this function during the
+    /// `#[init]` phase.
+    fn now() -> Self::Instant {
+        // Resolves to the inherent `LinkedMonotonic::now`, not a recursive
+        // call: inherent methods take priority over trait methods here.
+        Self::now()
     }
-}
 
-impl ops::SubAssign<Duration> for Instant {
-    fn sub_assign(&mut self, dur: Duration) {
-        self.inner = self.inner.wrapping_sub(dur.inner);
-    }
-}
+    /// Resets the counter to *zero*
+    ///
+    /// # Safety
+    ///
+    /// This function will be called *exactly once* by the RTFM runtime after `#[init]` returns and
+    /// before tasks can start; this is also the case in multi-core applications. User code must
+    /// *never* call this function.
+    unsafe fn reset() {
+        #[cfg(feature = "trace")]
+        rprintln!("LinkedMonotonic::reset()");
 
-impl ops::Sub<Duration> for Instant {
-    type Output = Self;
-    fn sub(mut self, dur: Duration) -> Self {
-        self -= dur;
-        self
+        // Pause
+        MSB::disable();
+        LSB::disable();
+        // Reset counter
+        MSB::reset_cnt();
+        LSB::reset_cnt();
+        // Continue
+        MSB::enable();
+        LSB::enable();
     }
-}
 
-impl ops::Sub for Instant {
-    type Output = Duration;
-    fn sub(self, other: Instant) -> Duration {
-        self.duration_since(other)
+    fn zero() -> Self::Instant {
+        Instant::<FREQ>::from_ticks(0)
     }
 }
 
-impl Ord for Instant {
-    fn cmp(&self, rhs: &Self) -> Ordering {
-        self.inner.cmp(&rhs.inner)
+#[cfg(test)]
+mod duration_since_tests {
+    use super::*;
+
+    // `duration_since` is generic over `LSB`/`MSB` only through its
+    // `impl` block's bounds, not its own body, so any `LinkedTimerChannel`
+    // pair stands in here; the hardware register access those impls define
+    // is never reached by this purely arithmetic function.
+    type Mono = LinkedMonotonic<pac::TIM2, pac::TIM3, 1_000>;
+
+    #[test]
+    fn duration_since_is_zero_for_equal_instants() {
+        let instant = Instant::<1_000>::from_ticks(42);
+        assert_eq!(Mono::duration_since(instant, instant).ticks(), 0);
     }
-}
 
-impl PartialOrd for Instant {
-    fn partial_cmp(&self, rhs: &Self) -> Option<Ordering> {
-        Some(self.cmp(rhs))
+    #[test]
+    fn duration_since_is_wrap_safe_across_the_32_bit_boundary() {
+        let earlier = Instant::<1_000>::from_ticks(u32::MAX - 4);
+        let instant = Instant::<1_000>::from_ticks(5);
+
+        // 5 ticks past `u32::MAX` wrapping to 0, i.e. 10 ticks elapsed.
+        assert_eq!(Mono::duration_since(instant, earlier).ticks(), 10);
     }
 }
 
-/// A `Duration` type to represent a span of time.
-#[derive(Clone, Copy, Default, Eq, Ord, PartialEq, PartialOrd)]
-pub struct Duration {
-    inner: u32,
+/// Adds the `secs`, `millis` and `micros` methods to the `u32` type.
+///
+/// Tick counts are derived from the monotonic's actual configured `FREQ`
+/// (the const generic on the returned [`Duration`]) rather than a hardcoded
+/// clock rate. Conversions saturate on overflow, and any non-zero request
+/// that would otherwise round down to zero ticks is rounded up to one tick
+/// instead, so sub-tick requests are never silently dropped.
+pub trait U32Ext {
+    /// Converts the `u32` value as seconds into ticks.
+    fn secs<const FREQ: u32>(self) -> Duration<FREQ>;
+
+    /// Converts the `u32` value as milliseconds into ticks.
+    fn millis<const FREQ: u32>(self) -> Duration<FREQ>;
+
+    /// Converts the `u32` value as microseconds into ticks.
+    fn micros<const FREQ: u32>(self) -> Duration<FREQ>;
 }
 
-impl Duration {
-    /// Creates a new `Duration` from the specified number of clock cycles
-    pub fn from_cycles(cycles: u32) -> Self {
-        Duration { inner: cycles }
-    }
-
-    /// Returns the total number of clock cycles contained by this `Duration`
-    pub fn as_cycles(&self) -> u32 {
-        self.inner
-    }
+/// Converts `value` microseconds into ticks at `FREQ` Hz, saturating once at
+/// the final narrowing to `u32` rather than at each unit conversion stage.
+///
+/// `secs`/`millis`/`micros` all funnel through here in `u64` microseconds so
+/// that e.g. `7_200u32.secs::<1_000>()` (2 hours) doesn't overflow `u32`
+/// midway through the `secs -> millis -> micros` chain and saturate to a
+/// much shorter duration than requested.
+fn micros_to_ticks<const FREQ: u32>(micros: u64) -> Duration<FREQ> {
+    assert!(FREQ > 0, "monotonic FREQ must be nonzero");
+
+    // `micros * FREQ` can overflow `u64` for realistic (large `micros`,
+    // large `FREQ`) inputs -- e.g. a 1_000_000_000-second request at a
+    // 32.768 kHz tick rate -- before the saturating narrowing to `u32` is
+    // ever reached. Do the multiply in `u128`, which can't overflow for any
+    // `u64` microsecond count times a `u32` `FREQ`, and only narrow
+    // (saturating) at the very end.
+    let ticks = (micros as u128 * FREQ as u128) / 1_000_000;
+    // Round a non-zero request up to at least one tick instead of letting
+    // it silently become a zero-length duration.
+    let ticks = if micros > 0 && ticks == 0 { 1 } else { ticks };
+
+    Duration::<FREQ>::from_ticks(ticks.min(u32::MAX as u128) as u32)
 }
 
-// Used internally by RTIC to convert the duration into a known type
-impl TryInto<u32> for Duration {
-    type Error = Infallible;
+impl U32Ext for u32 {
+    fn secs<const FREQ: u32>(self) -> Duration<FREQ> {
+        micros_to_ticks(self as u64 * 1_000_000)
+    }
 
-    fn try_into(self) -> Result<u32, Infallible> {
-        Ok(self.as_cycles())
+    fn millis<const FREQ: u32>(self) -> Duration<FREQ> {
+        micros_to_ticks(self as u64 * 1_000)
     }
-}
 
-impl ops::AddAssign for Duration {
-    fn add_assign(&mut self, dur: Duration) {
-        self.inner += dur.inner;
+    fn micros<const FREQ: u32>(self) -> Duration<FREQ> {
+        micros_to_ticks(self as u64)
     }
 }
 
-impl ops::Add for Duration {
-    type Output = Self;
-    fn add(self, other: Self) -> Self {
-        Duration {
-            inner: self.inner + other.inner,
-        }
+#[cfg(test)]
+mod u32_ext_tests {
+    use super::*;
+
+    #[test]
+    fn secs_does_not_saturate_before_reaching_micros_math() {
+        // Regression test: 7_200 s at 1 kHz is 7_200_000 ticks. Computing
+        // `secs -> millis -> micros` in saturating u32 space overflows
+        // partway through (7_200_000_000 millis-as-micros > u32::MAX) and
+        // used to silently truncate this to ~4295 ticks instead.
+        assert_eq!(7_200u32.secs::<1_000>().ticks(), 7_200_000);
     }
-}
 
-impl ops::Mul<u32> for Duration {
-    type Output = Self;
-    fn mul(self, other: u32) -> Self {
-        Duration {
-            inner: self.inner * other,
-        }
+    #[test]
+    fn millis_and_micros_agree_with_secs() {
+        assert_eq!(2u32.secs::<1_000>().ticks(), 2_000u32.millis::<1_000>().ticks());
+        assert_eq!(
+            2_000u32.millis::<1_000>().ticks(),
+            2_000_000u32.micros::<1_000>().ticks()
+        );
     }
-}
 
-impl ops::MulAssign<u32> for Duration {
-    fn mul_assign(&mut self, other: u32) {
-        *self = *self * other;
+    #[test]
+    fn sub_tick_requests_round_up_to_one_tick_instead_of_zero() {
+        assert_eq!(1u32.micros::<1_000>().ticks(), 1);
     }
-}
 
-impl ops::SubAssign for Duration {
-    fn sub_assign(&mut self, rhs: Duration) {
-        self.inner -= rhs.inner;
+    #[test]
+    fn large_requests_saturate_at_u32_max_ticks() {
+        assert_eq!(u32::MAX.secs::<1_000>().ticks(), u32::MAX);
     }
-}
 
-impl ops::Sub for Duration {
-    type Output = Self;
-    fn sub(self, rhs: Self) -> Self {
-        Duration {
-            inner: self.inner - rhs.inner,
-        }
+    #[test]
+    fn large_seconds_at_high_freq_does_not_overflow_and_saturates() {
+        // Regression test: at a realistic 32.768 kHz tick rate, 1 billion
+        // seconds (~31.7 years) multiplied out as u64 microseconds * FREQ
+        // overflows u64 before ever reaching the saturating min() in
+        // `micros_to_ticks`.
+        assert_eq!(1_000_000_000u32.secs::<32_768>().ticks(), u32::MAX);
     }
 }
-
-///// Adds the `secs`, `millis` and `micros` methods to the `u32` type
-/////
-///// This trait is only available on ARMv7-M
-//pub trait U32Ext {
-//    /// Converts the `u32` value as seconds into ticks
-//    fn secs(self) -> Duration;
-//
-//    /// Converts the `u32` value as milliseconds into ticks
-//    fn millis(self) -> Duration;
-//
-//    /// Converts the `u32` value as microseconds into ticks
-//    fn micros(self) -> Duration;
-//}
-//
-//impl U32Ext for u32 {
-//    fn secs(self) -> Duration {
-//        self.millis() * 1_000
-//    }
-//
-//    fn millis(self) -> Duration {
-//        self.micros() * 1_000
-//    }
-//
-//    fn micros(self) -> Duration {
-//        let frac = Tim1::ratio();
-//
-//        // 64 MHz / fraction / 1_000_000
-//        Duration {
-//            inner: (64 * frac.denominator * self) / frac.numerator,
-//        }
-//    }
-//}
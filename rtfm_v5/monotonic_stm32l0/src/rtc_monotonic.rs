@@ -0,0 +1,246 @@
+//! Low-power RTC-backed monotonic variant.
+//!
+//! Unlike [`LinkedMonotonic`](crate::monotonic_stm32l0::LinkedMonotonic), this
+//! ticks from the RTC domain (clocked from LSE/LSI), which keeps running in
+//! STOP mode. That lets `#[idle]` issue `WFI` and still have scheduled tasks
+//! wake the core up on time, instead of busy-looping to keep RTT alive.
+//!
+//! Time reads never log: tracing would mean RTT/ITM I/O on every `now()`
+//! call, which this module is specifically meant to avoid. Enable the
+//! `trace` cargo feature to get `rprintln!` diagnostics on the
+//! (non-hot-path) compare-arm call only.
+
+use core::sync::atomic::{AtomicU32, Ordering as AtomicOrdering};
+
+use fugit::{TimerDurationU32, TimerInstantU32};
+use rtic::Monotonic;
+#[cfg(feature = "trace")]
+use rtt_target::rprintln;
+use stm32l0xx_hal::{pac, rtc::Rtc};
+
+/// Tick frequency of [`RtcMonotonic`]: the RTC's synchronous prescaler
+/// output, 1024 Hz when fed from a 32.768 kHz LSE/LSI source.
+pub const RTC_FREQ: u32 = 1024;
+
+/// A point in time as tracked by [`RtcMonotonic`].
+pub type Instant = TimerInstantU32<RTC_FREQ>;
+
+/// A span of time as tracked by [`RtcMonotonic`].
+pub type Duration = TimerDurationU32<RTC_FREQ>;
+
+/// The target tick currently armed on the RTC wakeup timer, if any.
+static ARMED_COMPARE: AtomicU32 = AtomicU32::new(0);
+
+/// Implementor of the `rtic::Monotonic` trait backed by the STM32L0's RTC,
+/// for applications that need `#[idle]` to sleep with `WFI`/`WFE` instead of
+/// busy-looping.
+///
+/// Scheduling uses the RTC's wakeup timer as a compare channel: arming it
+/// schedules an interrupt `delta` RTC ticks in the future (the wakeup timer
+/// counts down and reloads, rather than comparing against an absolute
+/// value), so [`try_set_compare_at`](Self::try_set_compare_at) takes an
+/// absolute target tick and does the conversion.
+pub struct RtcMonotonic;
+
+impl RtcMonotonic {
+    /// Initialize the timer instance.
+    pub fn initialize(rtc: Rtc) {
+        // Explicitly drop the RTC instance so it cannot be reused or
+        // reconfigured outside of this module.
+        drop(rtc);
+    }
+
+    /// Returns the current RTC tick count.
+    ///
+    /// Allocation- and I/O-free: unlike [`LinkedMonotonic::now`](crate::monotonic_stm32l0::LinkedMonotonic::now),
+    /// this never traces, since it must also be safe to call right after
+    /// waking from STOP mode.
+    fn read_ticks() -> u32 {
+        let rtc = unsafe { &*pac::RTC::ptr() };
+
+        // Reading TR locks the calendar shadow registers (SSR/TR/DR) until
+        // DR is also read and RSF is cleared, so every read here has to
+        // follow the HAL's own `Rtc::now()` sequence: wait for RSF, read
+        // SSR then TR then DR, then clear RSF. Skipping the DR read (or
+        // never clearing RSF) leaves the shadow registers frozen after the
+        // first call, so every later call would just return that same
+        // snapshot forever.
+        while rtc.isr.read().rsf().bit_is_clear() {}
+
+        // SSR counts down from the synchronous prescaler reload value once
+        // per RTC clock tick and reloads on each second rollover (TR); a
+        // falling SSR within a second is rising elapsed time, so we invert
+        // it to get a monotonically increasing sub-second tick count.
+        let ssr = rtc.ssr.read().ss().bits() as u32;
+        let tr = rtc.tr.read().bits();
+        let _dr = rtc.dr.read().bits();
+        let seconds = bcd_to_binary(tr);
+
+        rtc.isr.write(|w| w.rsf().set_bit());
+
+        seconds
+            .wrapping_mul(RTC_FREQ)
+            .wrapping_add((RTC_FREQ - 1).wrapping_sub(ssr))
+    }
+
+    /// Returns an instant corresponding to "now".
+    pub fn now() -> Instant {
+        Instant::from_ticks(Self::read_ticks())
+    }
+
+    /// Arms the RTC wakeup timer so that it raises an interrupt once the
+    /// tick counter reaches `ticks`.
+    ///
+    /// Returns `true` if the wakeup timer was armed. Returns `false` if
+    /// `ticks` is already at or behind `now`, in which case the caller
+    /// should treat the task as immediately due instead of waiting for an
+    /// interrupt.
+    ///
+    /// # Long delays
+    ///
+    /// `WUTR` is only 16 bits wide, so a `delta` beyond `u16::MAX` ticks
+    /// (~64 s at [`RTC_FREQ`]) can't be programmed in one go. Rather than
+    /// truncating it, this arms the wakeup timer for the largest period
+    /// that fits (`delta.min(u16::MAX)`) and leaves it running: since the
+    /// wakeup timer auto-reloads the same countdown on every fire, it will
+    /// keep waking the core at that period until the real target is
+    /// reached. [`has_compared`](Self::has_compared) compares the actual
+    /// elapsed ticks against `ticks` rather than counting periods, so the
+    /// early, too-soon wakeups this produces are correctly reported as "not
+    /// yet" and the caller can just clear the flag and keep waiting.
+    pub fn try_set_compare_at(ticks: u32) -> bool {
+        let now = Self::read_ticks();
+        let delta = ticks.wrapping_sub(now);
+        if delta == 0 || delta > (1 << 31) {
+            return false;
+        }
+        let period = delta.min(u16::MAX as u32) as u16;
+
+        let rtc = unsafe { &*pac::RTC::ptr() };
+
+        ARMED_COMPARE.store(ticks, AtomicOrdering::Relaxed);
+
+        // Unlock write protection, disable the wakeup timer to reprogram it,
+        // load the new countdown value, then re-enable with its interrupt.
+        rtc.wpr.write(|w| unsafe { w.key().bits(0xCA) });
+        rtc.wpr.write(|w| unsafe { w.key().bits(0x53) });
+        rtc.cr.modify(|_, w| w.wute().clear_bit());
+        while rtc.isr.read().wutwf().bit_is_clear() {}
+        rtc.wutr.write(|w| unsafe { w.wut().bits(period) });
+        rtc.cr.modify(|_, w| w.wutie().set_bit().wute().set_bit());
+
+        #[cfg(feature = "trace")]
+        rprintln!(
+            "RtcMonotonic: armed wakeup, period {} ticks ({} until target)",
+            period,
+            delta
+        );
+
+        true
+    }
+
+    /// Clears the RTC wakeup timer's interrupt flag.
+    ///
+    /// Must be called from the bound interrupt handler after every wakeup.
+    pub fn clear_irq() {
+        let rtc = unsafe { &*pac::RTC::ptr() };
+        rtc.isr.modify(|_, w| w.wutf().clear_bit());
+        unsafe { &*pac::EXTI::ptr() }
+            .pr
+            .write(|w| w.pif20().set_bit());
+    }
+
+    /// Returns `true` if the RTC has woken up at or past the tick armed via
+    /// [`try_set_compare_at`](Self::try_set_compare_at).
+    pub fn has_compared() -> bool {
+        let rtc = unsafe { &*pac::RTC::ptr() };
+        if !rtc.isr.read().wutf().bit_is_set() {
+            return false;
+        }
+
+        Self::read_ticks().wrapping_sub(ARMED_COMPARE.load(AtomicOrdering::Relaxed)) < (1 << 31)
+    }
+}
+
+/// Converts the RTC's BCD-encoded time-of-day register into a plain second
+/// count since midnight.
+fn bcd_to_binary(tr: u32) -> u32 {
+    let ht = (tr >> 20) & 0x3;
+    let hu = (tr >> 16) & 0xf;
+    let mnt = (tr >> 12) & 0x7;
+    let mnu = (tr >> 8) & 0xf;
+    let st = (tr >> 4) & 0x7;
+    let su = tr & 0xf;
+
+    let hours = ht * 10 + hu;
+    let minutes = mnt * 10 + mnu;
+    let seconds = st * 10 + su;
+
+    (hours * 3600) + (minutes * 60) + seconds
+}
+
+#[cfg(test)]
+mod bcd_to_binary_tests {
+    use super::*;
+
+    #[test]
+    fn midnight_is_zero() {
+        assert_eq!(bcd_to_binary(0), 0);
+    }
+
+    #[test]
+    fn decodes_bcd_hours_minutes_seconds() {
+        // 13:45:09 as packed BCD: HT=1 HU=3 MNT=4 MNU=5 ST=0 SU=9.
+        let tr = (0x1 << 20) | (0x3 << 16) | (0x4 << 12) | (0x5 << 8) | (0x0 << 4) | 0x9;
+        assert_eq!(bcd_to_binary(tr), 13 * 3600 + 45 * 60 + 9);
+    }
+}
+
+impl Monotonic for RtcMonotonic {
+    type Instant = Instant;
+
+    fn ratio() -> rtic::Fraction {
+        // The RTC ticks independently of the system clock, so there is no
+        // fixed sysclock-to-tick relationship to report here.
+        rtic::Fraction {
+            numerator: 1,
+            denominator: 1,
+        }
+    }
+
+    /// Returns the current time
+    ///
+    /// # Correctness
+    ///
+    /// This function is *allowed* to return nonsensical values if called before `reset` is invoked
+    /// by the runtime. Therefore application authors should *not* call this function during the
+    /// `#[init]` phase.
+    fn now() -> Self::Instant {
+        Self::now()
+    }
+
+    /// Resets the counter to *zero*
+    ///
+    /// # Safety
+    ///
+    /// This function will be called *exactly once* by the RTFM runtime after `#[init]` returns and
+    /// before tasks can start; this is also the case in multi-core applications. User code must
+    /// *never* call this function.
+    unsafe fn reset() {
+        let rtc = &*pac::RTC::ptr();
+        rtc.wpr.write(|w| w.key().bits(0xCA));
+        rtc.wpr.write(|w| w.key().bits(0x53));
+
+        // TR/DR are shadowed and only take a write once the RTC is in
+        // Initialization mode (ISR.INITF set); writing them beforehand is
+        // silently ignored by the calendar logic.
+        rtc.isr.modify(|_, w| w.init().set_bit());
+        while rtc.isr.read().initf().bit_is_clear() {}
+        rtc.tr.reset();
+        rtc.isr.modify(|_, w| w.init().clear_bit());
+    }
+
+    fn zero() -> Self::Instant {
+        Instant::from_ticks(0)
+    }
+}